@@ -1,125 +1,383 @@
+use std::cmp;
 use std::io;
-use std::io::Read;
 use std::iter::Peekable;
 use std::collections::HashMap;
 
-use value::Value;
+use value::{Value, BValue};
 
 #[derive(Debug)]
 pub enum DecodeError {
     IOError(io::Error),
-    UnexpectedEndOfBuffer,
-    UnexpectedCharacter(String)
-}
-
-macro_rules! try_read {
-    ($e: expr) => (
-        match $e.next() {
-            None => return Err(DecodeError::UnexpectedEndOfBuffer),
-            Some(Err(e)) => return Err(DecodeError::IOError(e)),
-            Some(Ok(c)) => c,
-        }
-    );
-}
-macro_rules! try_peek {
-    ($e: expr) => ({
-        let tmp = match $e.peek() {
-            None => return Err(DecodeError::UnexpectedEndOfBuffer),
-            Some(&Err(_)) => {
-                // We need an owned version of IOError. If peek() raised one,
-                // hopefully, next() will, so let's do it.
-                // Unfortunately, we cannot do it now because the reader is
-                // still mutably borrowed, so let's defer the call.
-                None
-            }
-            Some(&Ok(c)) => Some(c),
+    /// End of input was reached in the middle of a value; carries the byte
+    /// offset at which more input was expected.
+    UnexpectedEndOfBuffer(usize),
+    UnexpectedCharacter(String),
+    /// An integer was not in canonical form (a leading zero, or `-0`).
+    NonCanonicalInteger(String),
+    /// Dictionary keys were not in strictly ascending byte order.
+    UnsortedKeys(String),
+    /// The same dictionary key appeared twice.
+    DuplicateKey(String),
+    /// Bytes remained after a top-level value in a strict decode.
+    TrailingData(String),
+    /// An integer or string-length prefix did not fit in its target type.
+    IntegerOverflow(String),
+}
+
+/// A source of bytes for the decoder.
+///
+/// Abstracts over an in-memory slice and an arbitrary `io::Read`, and tracks the
+/// byte offset reached so that errors can point at the exact location of
+/// malformed input. `peek_byte` returns the next byte without consuming it;
+/// `next_byte` consumes it. Both yield `Ok(None)` at end of input.
+pub trait Reader {
+    fn next_byte(&mut self) -> Result<Option<u8>, DecodeError>;
+    fn peek_byte(&mut self) -> Result<Option<u8>, DecodeError>;
+    fn position(&self) -> usize;
+}
+
+/// A `Reader` backed by a byte slice: O(1) peek and no allocation.
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> SliceReader<'a> {
+        SliceReader { data: data, pos: 0 }
+    }
+}
+
+impl<'a> Reader for SliceReader<'a> {
+    fn next_byte(&mut self) -> Result<Option<u8>, DecodeError> {
+        match self.data.get(self.pos) {
+            Some(&byte) => { self.pos += 1; Ok(Some(byte)) }
+            None => Ok(None),
+        }
+    }
+    fn peek_byte(&mut self) -> Result<Option<u8>, DecodeError> {
+        Ok(self.data.get(self.pos).cloned())
+    }
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+/// A `Reader` wrapping any `io::Read`. Unlike a bare `Peekable<io::Bytes<R>>`,
+/// this still tracks the consumed byte count for error reporting.
+pub struct IoReader<R: io::Read> {
+    bytes: Peekable<io::Bytes<R>>,
+    pos: usize,
+}
+
+impl<R: io::Read> IoReader<R> {
+    pub fn new(reader: R) -> IoReader<R> {
+        IoReader { bytes: reader.bytes().peekable(), pos: 0 }
+    }
+}
+
+impl<R: io::Read> Reader for IoReader<R> {
+    fn next_byte(&mut self) -> Result<Option<u8>, DecodeError> {
+        match self.bytes.next() {
+            None => Ok(None),
+            Some(Err(e)) => Err(DecodeError::IOError(e)),
+            Some(Ok(byte)) => { self.pos += 1; Ok(Some(byte)) }
+        }
+    }
+    fn peek_byte(&mut self) -> Result<Option<u8>, DecodeError> {
+        // If peek() surfaced an IO error we cannot take an owned copy while the
+        // reader is still borrowed, so defer the next() call until after the
+        // match; next() is expected to yield that same error.
+        let peeked = match self.bytes.peek() {
+            None => return Ok(None),
+            Some(&Ok(byte)) => Some(byte),
+            Some(&Err(_)) => None,
         };
-        match tmp {
-            Some(c) => c,
-            None => return Err(DecodeError::IOError($e.next().unwrap().unwrap_err()))
+        match peeked {
+            Some(byte) => Ok(Some(byte)),
+            None => Err(DecodeError::IOError(self.bytes.next().unwrap().unwrap_err())),
         }
-    });
+    }
+    fn position(&self) -> usize {
+        self.pos
+    }
+}
+
+/// Consumes the next byte, or fails with the current offset if at EOF.
+fn expect_byte<R: Reader>(reader: &mut R) -> Result<u8, DecodeError> {
+    match try!(reader.next_byte()) {
+        Some(byte) => Ok(byte),
+        None => Err(DecodeError::UnexpectedEndOfBuffer(reader.position())),
+    }
 }
 
-fn read_integer<R: io::Read>(bytes: &mut Peekable<io::Bytes<R>>) -> Result<i64, DecodeError> {
-    let mut res = 0i64;
-    let first_digit = try_peek!(bytes);
-    let multiplicator = if first_digit as char == '-' { try_read!(bytes); -1 } else { 1 };
+/// Peeks at the next byte, or fails with the current offset if at EOF.
+fn expect_peek<R: Reader>(reader: &mut R) -> Result<u8, DecodeError> {
+    match try!(reader.peek_byte()) {
+        Some(byte) => Ok(byte),
+        None => Err(DecodeError::UnexpectedEndOfBuffer(reader.position())),
+    }
+}
+
+fn read_integer<R: Reader>(reader: &mut R, strict: bool) -> Result<i64, DecodeError> {
+    let sign = try!(expect_peek(reader));
+    let negative = if sign as char == '-' { try!(expect_byte(reader)); true } else { false };
+    let mut ndigits = 0usize;
+    let mut first_digit = 0u8;
+    // Accumulate in the negative half of the range so that i64::MIN, whose
+    // magnitude does not fit in a positive i64, is representable.
+    let mut acc = 0i64;
     loop {
-        let digit = try_read!(bytes);
+        let offset = reader.position();
+        let digit = try!(expect_byte(reader));
         match digit as char {
             'e' => break,
-            '0' ... '9' => res = res*10 + (digit as i64 - ('0' as i64)),
-            _ => return Err(DecodeError::UnexpectedCharacter(format!("'{}' while reading an integer.", digit as char))),
+            '0' ... '9' => {
+                if ndigits == 0 { first_digit = digit; }
+                ndigits += 1;
+                let value = digit as i64 - ('0' as i64);
+                acc = try!(acc.checked_mul(10).and_then(|a| a.checked_sub(value))
+                    .ok_or_else(|| DecodeError::IntegerOverflow(format!("integer magnitude exceeds i64 range at offset {}", offset))));
+            }
+            _ => return Err(DecodeError::UnexpectedCharacter(format!("'{}' at offset {} while reading an integer.", digit as char, offset))),
         }
     };
-    Ok(multiplicator * res)
+    let res = if negative {
+        acc
+    } else {
+        try!(acc.checked_neg().ok_or_else(|| DecodeError::IntegerOverflow("integer magnitude exceeds i64 range".to_owned())))
+    };
+    if strict {
+        if ndigits == 0 {
+            return Err(DecodeError::NonCanonicalInteger("empty integer".to_owned()));
+        }
+        if first_digit == '0' as u8 && ndigits > 1 {
+            return Err(DecodeError::NonCanonicalInteger(format!("leading zero in integer '{}'", res)));
+        }
+        if negative && res == 0 {
+            return Err(DecodeError::NonCanonicalInteger("negative zero".to_owned()));
+        }
+    }
+    Ok(res)
 }
 
-fn read_list<R: io::Read>(bytes: &mut Peekable<io::Bytes<R>>) -> Result<Vec<Value>, DecodeError> {
+fn read_list<R: Reader>(reader: &mut R, strict: bool) -> Result<Vec<Value>, DecodeError> {
     let mut res = Vec::<Value>::new();
     loop {
-        let digit = try_peek!(bytes);
-        match digit as char {
-            'e' => break,
-            _ => res.push(try!(read(bytes))),
+        let byte = try!(expect_peek(reader));
+        match byte as char {
+            'e' => { try!(expect_byte(reader)); break },
+            _ => res.push(try!(read_value(reader, strict))),
         }
     }
     Ok(res)
 }
 
-fn read_string<R: io::Read>(bytes: &mut Peekable<io::Bytes<R>>, first_byte: u8) -> Result<Vec<u8>, DecodeError> {
+fn read_string<R: Reader>(reader: &mut R, first_byte: u8) -> Result<Vec<u8>, DecodeError> {
     assert!(first_byte >= '0' as u8);
     assert!(first_byte <= '9' as u8);
+    let length = try!(read_string_length(reader, first_byte));
+    // Reserve a bounded amount rather than the declared length: an over-large
+    // but non-wrapping length (e.g. "18446744073709551615:") would otherwise
+    // abort with "capacity overflow" before a single byte is read. Any genuine
+    // shortfall surfaces as UnexpectedEndOfBuffer from expect_byte below.
+    let mut res = Vec::new();
+    res.reserve(cmp::min(length, 4096));
+    for _ in 0..length {
+        res.push(try!(expect_byte(reader)));
+    }
+    Ok(res)
+}
+
+/// Parses a string-length prefix up to and including its `:` terminator.
+fn read_string_length<R: Reader>(reader: &mut R, first_byte: u8) -> Result<usize, DecodeError> {
     let mut length = first_byte as usize - ('0' as usize);
     loop {
-        let digit = try_read!(bytes);
+        let offset = reader.position();
+        let digit = try!(expect_byte(reader));
         match digit as char {
             ':' => break,
-            '0' ... '9' => length = length*10 + digit as usize - ('0' as usize),
-            _ => return Err(DecodeError::UnexpectedCharacter(format!("'{}' while reading a string length", digit as char)))
+            '0' ... '9' => {
+                let value = digit as usize - ('0' as usize);
+                length = try!(length.checked_mul(10).and_then(|l| l.checked_add(value))
+                    .ok_or_else(|| DecodeError::IntegerOverflow(format!("string length exceeds usize range at offset {}", offset))));
+            }
+            _ => return Err(DecodeError::UnexpectedCharacter(format!("'{}' at offset {} while reading a string length", digit as char, offset)))
         }
     }
-    let mut res = Vec::new();
-    res.reserve(length);
-    for _ in 0..length {
-        res.push(try_read!(bytes));
-    }
-    Ok(res)
+    Ok(length)
 }
 
-fn read_dict<R: io::Read>(bytes: &mut Peekable<io::Bytes<R>>) -> Result<HashMap<Vec<u8>, Value>, DecodeError> {
+fn read_dict<R: Reader>(reader: &mut R, strict: bool) -> Result<HashMap<Vec<u8>, Value>, DecodeError> {
     let mut res = HashMap::<Vec<u8>, Value>::new();
+    let mut previous_key: Option<Vec<u8>> = None;
     loop {
-        let first_byte = try_read!(bytes);
+        let offset = reader.position();
+        let first_byte = try!(expect_byte(reader));
         if first_byte as char == 'e' {
             break
         }
-        res.insert(try!(read_string(bytes, first_byte)), try!(read(bytes)));
+        if first_byte < '0' as u8 || first_byte > '9' as u8 {
+            return Err(DecodeError::UnexpectedCharacter(format!("'{}' at offset {} instead of a dictionary key length", first_byte as char, offset)));
+        }
+        let key = try!(read_string(reader, first_byte));
+        if strict {
+            if let Some(ref previous) = previous_key {
+                if *previous == key {
+                    return Err(DecodeError::DuplicateKey(format!("key {:?} appears more than once", key)));
+                } else if *previous > key {
+                    return Err(DecodeError::UnsortedKeys(format!("key {:?} follows {:?}", key, previous)));
+                }
+            }
+        }
+        let value = try!(read_value(reader, strict));
+        previous_key = Some(key.clone());
+        res.insert(key, value);
     }
     Ok(res)
 }
 
 
-pub fn read<R: io::Read>(bytes: &mut Peekable<io::Bytes<R>>) -> Result<Value, DecodeError> {
-    let byte = try_read!(bytes);
+fn read_value<R: Reader>(reader: &mut R, strict: bool) -> Result<Value, DecodeError> {
+    let offset = reader.position();
+    let byte = try!(expect_byte(reader));
     match byte as char {
-        'i' => read_integer(bytes).map(Value::Integer),
-        'l' => read_list(bytes).map(Value::List),
-        'd' => read_dict(bytes).map(Value::Dictionary),
-        '0' ... '9' => read_string(bytes, byte).map(Value::String),
-        _ => Err(DecodeError::UnexpectedCharacter(format!("'{}' instead of the first byte of an object.", byte)))
+        'i' => read_integer(reader, strict).map(Value::Integer),
+        'l' => read_list(reader, strict).map(Value::List),
+        'd' => read_dict(reader, strict).map(Value::Dictionary),
+        '0' ... '9' => read_string(reader, byte).map(Value::String),
+        _ => Err(DecodeError::UnexpectedCharacter(format!("'{}' at offset {} instead of the first byte of an object.", byte as char, offset)))
+    }
+}
+
+pub fn read<R: Reader>(reader: &mut R) -> Result<Value, DecodeError> {
+    read_value(reader, false)
+}
+
+/// Like `read`, but rejects non-canonical encodings: integers with a leading
+/// zero (unless the value is exactly `0`), a `-0`, dictionary keys that are not
+/// in strictly ascending byte order or that are duplicated, and any bytes left
+/// over after the value. Intended as the top-level entry point for decoding
+/// untrusted wire data where parser-differential behaviour matters.
+pub fn read_strict<R: Reader>(reader: &mut R) -> Result<Value, DecodeError> {
+    let value = try!(read_value(reader, true));
+    match try!(reader.peek_byte()) {
+        None => Ok(value),
+        Some(byte) => Err(DecodeError::TrailingData(format!("'{}' at offset {} after a top-level value", byte as char, reader.position()))),
     }
 }
 
 pub fn decode(sl: &[u8]) -> Result<Value, DecodeError> {
-    read(&mut sl.bytes().peekable())
+    read(&mut SliceReader::new(sl))
+}
+
+/// Decode exactly one value from `sl` in canonical (strict) form, rejecting any
+/// trailing bytes. See `read_strict` for the exact rules.
+pub fn decode_strict(sl: &[u8]) -> Result<Value, DecodeError> {
+    read_strict(&mut SliceReader::new(sl))
+}
+
+fn read_string_borrowed<'a>(reader: &mut SliceReader<'a>, first_byte: u8) -> Result<&'a [u8], DecodeError> {
+    assert!(first_byte >= '0' as u8);
+    assert!(first_byte <= '9' as u8);
+    let length = try!(read_string_length(reader, first_byte));
+    let start = reader.pos;
+    // `start <= data.len()` always holds here, so subtract to avoid the
+    // add-overflow that a huge but usize-fitting `length` would otherwise cause.
+    if length > reader.data.len() - start {
+        return Err(DecodeError::UnexpectedEndOfBuffer(reader.data.len()));
+    }
+    let res = &reader.data[start..start + length];
+    reader.pos += length;
+    Ok(res)
+}
+
+fn read_list_borrowed<'a>(reader: &mut SliceReader<'a>) -> Result<Vec<BValue<'a>>, DecodeError> {
+    let mut res = Vec::new();
+    loop {
+        let byte = try!(expect_peek(reader));
+        match byte as char {
+            'e' => { try!(expect_byte(reader)); break },
+            _ => res.push(try!(read_borrowed_value(reader))),
+        }
+    }
+    Ok(res)
+}
+
+fn read_dict_borrowed<'a>(reader: &mut SliceReader<'a>) -> Result<HashMap<&'a [u8], BValue<'a>>, DecodeError> {
+    let mut res = HashMap::new();
+    loop {
+        let offset = reader.position();
+        let first_byte = try!(expect_byte(reader));
+        if first_byte as char == 'e' {
+            break
+        }
+        if first_byte < '0' as u8 || first_byte > '9' as u8 {
+            return Err(DecodeError::UnexpectedCharacter(format!("'{}' at offset {} instead of a dictionary key length", first_byte as char, offset)));
+        }
+        let key = try!(read_string_borrowed(reader, first_byte));
+        res.insert(key, try!(read_borrowed_value(reader)));
+    }
+    Ok(res)
+}
+
+fn read_borrowed_value<'a>(reader: &mut SliceReader<'a>) -> Result<BValue<'a>, DecodeError> {
+    let offset = reader.position();
+    let byte = try!(expect_byte(reader));
+    match byte as char {
+        'i' => read_integer(reader, false).map(BValue::Integer),
+        'l' => read_list_borrowed(reader).map(BValue::List),
+        'd' => read_dict_borrowed(reader).map(BValue::Dictionary),
+        '0' ... '9' => read_string_borrowed(reader, byte).map(BValue::String),
+        _ => Err(DecodeError::UnexpectedCharacter(format!("'{}' at offset {} instead of the first byte of an object.", byte as char, offset)))
+    }
+}
+
+/// Decodes a single value from an in-memory slice without copying: every string
+/// in the returned `BValue` borrows directly from `sl`. Use `BValue::to_owned`
+/// if an owning `Value` is needed afterwards.
+pub fn decode_borrowed(sl: &[u8]) -> Result<BValue, DecodeError> {
+    read_borrowed_value(&mut SliceReader::new(sl))
+}
+
+/// A pull-based decoder that yields successive bencode values read from `reader`
+/// until it reaches EOF.
+///
+/// Each call to `next()` decodes exactly one value by calling `read` once. When
+/// the underlying reader is at EOF *between* two values, iteration stops cleanly
+/// by returning `None`; if EOF happens in the middle of a value, the final item
+/// is `Err(DecodeError::UnexpectedEndOfBuffer)`. This is handy to consume a
+/// socket or stdin stream carrying several length-prefixed bencode messages
+/// back-to-back without rebuilding a `Peekable` for every message.
+pub struct Decoder<R: io::Read> {
+    reader: IoReader<R>,
+}
+
+impl<R: io::Read> Decoder<R> {
+    pub fn new(reader: R) -> Decoder<R> {
+        Decoder { reader: IoReader::new(reader) }
+    }
+}
+
+impl<R: io::Read> Iterator for Decoder<R> {
+    type Item = Result<Value, DecodeError>;
+
+    fn next(&mut self) -> Option<Result<Value, DecodeError>> {
+        match self.reader.peek_byte() {
+            // True EOF between values: stop cleanly.
+            Ok(None) => None,
+            // A pending IO error surfaces as the next decoded item.
+            Err(e) => Some(Err(e)),
+            // A ready byte: decode exactly one value.
+            Ok(Some(_)) => Some(read(&mut self.reader)),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use value::Value;
+    use value::{Value, BValue};
     use super::*;
 
     #[test]
@@ -152,4 +410,122 @@ mod tests {
         expected.insert(b"spam".to_vec(), Value::String(b"eggs".to_vec()));
         assert_eq!(decode(b"d3:cow3:moo4:spam4:eggse").unwrap(), Value::Dictionary(expected));
     }
+
+    #[test]
+    fn strict_integer() {
+        assert_eq!(decode_strict(b"i0e").unwrap(), Value::Integer(0));
+        assert_eq!(decode_strict(b"i-1234e").unwrap(), Value::Integer(-1234));
+        assert!(decode_strict(b"i007e").is_err());
+        assert!(decode_strict(b"i-0e").is_err());
+        // The lenient decoder still accepts these.
+        assert_eq!(decode(b"i007e").unwrap(), Value::Integer(7));
+    }
+
+    #[test]
+    fn strict_trailing_data() {
+        assert!(decode_strict(b"i1234eaaaa").is_err());
+        assert_eq!(decode_strict(b"i1234e").unwrap(), Value::Integer(1234));
+    }
+
+    #[test]
+    fn strict_keys() {
+        assert!(decode_strict(b"d3:cow3:moo4:spam4:eggse").is_ok());
+        // Unsorted keys ("spam" before "cow").
+        assert!(decode_strict(b"d4:spam4:eggs3:cow3:mooe").is_err());
+        // Duplicate keys.
+        assert!(decode_strict(b"d3:cow3:moo3:cow3:mooe").is_err());
+    }
+
+    #[test]
+    fn integer_boundaries() {
+        assert_eq!(decode(b"i9223372036854775807e").unwrap(), Value::Integer(i64::max_value()));
+        assert_eq!(decode(b"i-9223372036854775808e").unwrap(), Value::Integer(i64::min_value()));
+        match decode(b"i99999999999999999999e") {
+            Err(DecodeError::IntegerOverflow(_)) => (),
+            other => panic!("Expected IntegerOverflow, got {:?}", other),
+        }
+        // One past i64::MAX.
+        assert!(decode(b"i9223372036854775808e").is_err());
+    }
+
+    #[test]
+    fn string_length_overflow() {
+        match decode(b"99999999999999999999:") {
+            Err(DecodeError::IntegerOverflow(_)) => (),
+            other => panic!("Expected IntegerOverflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn huge_string_length_does_not_panic() {
+        // A usize::MAX-sized declared length must be rejected with an error, not
+        // abort with a capacity overflow before any byte is read.
+        assert!(decode(b"18446744073709551615:abc").is_err());
+    }
+
+    #[test]
+    fn huge_string_length_borrowed_does_not_panic() {
+        assert!(decode_borrowed(b"18446744073709551615:abc").is_err());
+    }
+
+    #[test]
+    fn dict_key_not_a_string() {
+        // A dictionary key that does not begin with a length digit must be a
+        // positioned error, not a panic.
+        match decode(b"di1e3:fooe") {
+            Err(DecodeError::UnexpectedCharacter(ref msg)) => assert!(msg.contains("offset 1")),
+            other => panic!("Expected UnexpectedCharacter, got {:?}", other),
+        }
+        assert!(decode_strict(b"di1e3:fooe").is_err());
+        assert!(decode_borrowed(b"di1e3:fooe").is_err());
+    }
+
+    #[test]
+    fn error_position() {
+        match decode(b"i12a34e") {
+            Err(DecodeError::UnexpectedCharacter(ref msg)) => assert!(msg.contains("offset 3")),
+            other => panic!("Expected UnexpectedCharacter, got {:?}", other),
+        }
+        match decode(b"5:abc") {
+            Err(DecodeError::UnexpectedEndOfBuffer(offset)) => assert_eq!(offset, 5),
+            other => panic!("Expected UnexpectedEndOfBuffer, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn borrowed() {
+        assert_eq!(decode_borrowed(b"5:abcde").unwrap(), BValue::String(b"abcde"));
+        assert_eq!(decode_borrowed(b"i1234e").unwrap(), BValue::Integer(1234));
+        let mut expected = HashMap::new();
+        expected.insert(&b"cow"[..], BValue::String(b"moo"));
+        expected.insert(&b"spam"[..], BValue::String(b"eggs"));
+        assert_eq!(decode_borrowed(b"d3:cow3:moo4:spam4:eggse").unwrap(), BValue::Dictionary(expected));
+    }
+
+    #[test]
+    fn borrowed_to_owned() {
+        let owned = decode_borrowed(b"d3:cow3:mooe").unwrap().to_owned();
+        let mut expected = HashMap::new();
+        expected.insert(b"cow".to_vec(), Value::String(b"moo".to_vec()));
+        assert_eq!(owned, Value::Dictionary(expected));
+    }
+
+    #[test]
+    fn streaming() {
+        let mut decoder = Decoder::new(&b"i1234e5:abcdeli0ee"[..]);
+        assert_eq!(decoder.next().unwrap().unwrap(), Value::Integer(1234));
+        assert_eq!(decoder.next().unwrap().unwrap(), Value::String(b"abcde".to_vec()));
+        assert_eq!(decoder.next().unwrap().unwrap(), Value::List(vec![Value::Integer(0)]));
+        assert!(decoder.next().is_none());
+    }
+
+    #[test]
+    fn streaming_truncated() {
+        let mut decoder = Decoder::new(&b"i1234e5:abc"[..]);
+        assert_eq!(decoder.next().unwrap().unwrap(), Value::Integer(1234));
+        match decoder.next() {
+            Some(Err(DecodeError::UnexpectedEndOfBuffer(_))) => (),
+            other => panic!("Expected UnexpectedEndOfBuffer, got {:?}", other),
+        }
+    }
 }
@@ -1,6 +1,7 @@
 //! Functions to read the content of a dictionary and checking types.
 
 use std::collections::HashMap;
+use std::fmt;
 use std::string::FromUtf8Error;
 
 use value::Value;
@@ -12,6 +13,17 @@ pub enum HelperDecodeError {
     BadType(String),
     MissingKey(String),
     FromUtf8Error(FromUtf8Error),
+    /// Wraps an inner error with the dictionary key or list index at which it
+    /// occurred, so the full path traversed to a failure can be reported.
+    InPath(String, Box<HelperDecodeError>),
+}
+
+impl HelperDecodeError {
+    /// Records that this error happened while traversing `segment` (a dictionary
+    /// key or a `[index]`).
+    fn at(self, segment: String) -> HelperDecodeError {
+        HelperDecodeError::InPath(segment, Box::new(self))
+    }
 }
 
 /// Pops a BValue::Integer from a HashMap.
@@ -72,6 +84,117 @@ pub fn pop_value_utf8_string_option(map: &mut HashMap<Vec<u8>, Value>, key: Stri
 }
 
 
+/// A composable decoder that extracts a typed value out of a `Value` tree.
+///
+/// The built-ins below can be nested so that, for instance, a caller can write
+/// `Field("peers", List(Field("ip", Utf8)))` in a single expression instead of
+/// a ladder of `pop_value_*` calls. When a nested decoder fails, the key path
+/// traversed is recorded in `HelperDecodeError::InPath`.
+pub trait Decode {
+    type Output;
+    fn decode(&self, v: &Value) -> Result<Self::Output, HelperDecodeError>;
+}
+
+/// Decodes a `Value::Integer`.
+pub struct Integer;
+
+impl Decode for Integer {
+    type Output = i64;
+    fn decode(&self, v: &Value) -> Result<i64, HelperDecodeError> {
+        match *v {
+            Value::Integer(i) => Ok(i),
+            ref other => Err(HelperDecodeError::BadType(format!("Expected integer, got: {:?}", other))),
+        }
+    }
+}
+
+/// Decodes a `Value::String` into a UTF-8 `String`.
+pub struct Utf8;
+
+impl Decode for Utf8 {
+    type Output = String;
+    fn decode(&self, v: &Value) -> Result<String, HelperDecodeError> {
+        match *v {
+            Value::String(ref s) => match String::from_utf8(s.clone()) {
+                Ok(decoded) => Ok(decoded),
+                Err(e) => Err(HelperDecodeError::FromUtf8Error(e)),
+            },
+            ref other => Err(HelperDecodeError::BadType(format!("Expected UTF8 string, got: {:?}", other))),
+        }
+    }
+}
+
+/// Decodes a `Value::String` into raw bytes.
+pub struct Bytes;
+
+impl Decode for Bytes {
+    type Output = Vec<u8>;
+    fn decode(&self, v: &Value) -> Result<Vec<u8>, HelperDecodeError> {
+        match *v {
+            Value::String(ref s) => Ok(s.clone()),
+            ref other => Err(HelperDecodeError::BadType(format!("Expected byte string, got: {:?}", other))),
+        }
+    }
+}
+
+/// Decodes every element of a `Value::List` with the inner decoder.
+pub struct List<D>(pub D);
+
+impl<D: Decode> Decode for List<D> {
+    type Output = Vec<D::Output>;
+    fn decode(&self, v: &Value) -> Result<Vec<D::Output>, HelperDecodeError> {
+        match *v {
+            Value::List(ref items) => {
+                let mut res = Vec::with_capacity(items.len());
+                for (i, item) in items.iter().enumerate() {
+                    match self.0.decode(item) {
+                        Ok(decoded) => res.push(decoded),
+                        Err(e) => return Err(e.at(format!("[{}]", i))),
+                    }
+                }
+                Ok(res)
+            }
+            ref other => Err(HelperDecodeError::BadType(format!("Expected list, got: {:?}", other))),
+        }
+    }
+}
+
+/// RecordDot-style access into a `Value::Dictionary` by key, decoding the value
+/// found there with the inner decoder.
+pub struct Field<D>(pub &'static str, pub D);
+
+impl<D: Decode> Decode for Field<D> {
+    type Output = D::Output;
+    fn decode(&self, v: &Value) -> Result<D::Output, HelperDecodeError> {
+        match *v {
+            Value::Dictionary(ref map) => match map.get(self.0.as_bytes()) {
+                Some(inner) => self.1.decode(inner).map_err(|e| e.at(self.0.to_owned())),
+                None => Err(HelperDecodeError::MissingKey(self.0.to_owned())),
+            },
+            ref other => Err(HelperDecodeError::BadType(format!("Expected dictionary for key '{}', got: {:?}", self.0, other))),
+        }
+    }
+}
+
+/// Decodes a value with the inner decoder, then accepts it only if it is one of
+/// a whitelisted set, rejecting anything else with `BadType`.
+pub struct OneOf<D: Decode>(pub D, pub Vec<D::Output>);
+
+impl<D> Decode for OneOf<D>
+    where D: Decode, D::Output: PartialEq + fmt::Debug
+{
+    type Output = D::Output;
+    fn decode(&self, v: &Value) -> Result<D::Output, HelperDecodeError> {
+        let decoded = try!(self.0.decode(v));
+        if self.1.iter().any(|allowed| *allowed == decoded) {
+            Ok(decoded)
+        } else {
+            Err(HelperDecodeError::BadType(format!("Value {:?} is not one of the allowed values {:?}", decoded, self.1)))
+        }
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
@@ -89,4 +212,39 @@ mod tests {
         assert_eq!(pop_value_integer(&mut map, "quux".to_owned()).unwrap(), 42);
         assert_eq!(map, HashMap::new());
     }
+
+    #[test]
+    fn test_combinators() {
+        // { "peers": [ { "ip": "1.2.3.4" }, { "ip": "5.6.7.8" } ] }
+        let mut peer1 = HashMap::new();
+        peer1.insert(b"ip".to_vec(), Value::String(b"1.2.3.4".to_vec()));
+        let mut peer2 = HashMap::new();
+        peer2.insert(b"ip".to_vec(), Value::String(b"5.6.7.8".to_vec()));
+        let mut root = HashMap::new();
+        root.insert(b"peers".to_vec(), Value::List(vec![Value::Dictionary(peer1), Value::Dictionary(peer2)]));
+        let value = Value::Dictionary(root);
+
+        let ips = Field("peers", List(Field("ip", Utf8))).decode(&value).unwrap();
+        assert_eq!(ips, vec!["1.2.3.4".to_owned(), "5.6.7.8".to_owned()]);
+    }
+
+    #[test]
+    fn test_one_of() {
+        assert_eq!(OneOf(Utf8, vec!["GET".to_owned(), "PUT".to_owned()]).decode(&Value::String(b"GET".to_vec())).unwrap(), "GET".to_owned());
+        assert!(OneOf(Utf8, vec!["GET".to_owned()]).decode(&Value::String(b"DELETE".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_path_reported() {
+        let mut peer = HashMap::new();
+        peer.insert(b"ip".to_vec(), Value::Integer(42));
+        let mut root = HashMap::new();
+        root.insert(b"peers".to_vec(), Value::List(vec![Value::Dictionary(peer)]));
+        let value = Value::Dictionary(root);
+
+        match Field("peers", List(Field("ip", Utf8))).decode(&value) {
+            Err(HelperDecodeError::InPath(ref key, _)) => assert_eq!(key, "peers"),
+            other => panic!("Expected an InPath error, got {:?}", other),
+        }
+    }
 }
@@ -1,9 +1,22 @@
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+
 mod value;
 mod decoder;
 mod encoder;
 
 pub mod decoding_helpers;
 
-pub use value::Value;
-pub use decoder::{read, decode, DecodeError};
+#[cfg(feature = "serde")]
+pub mod serde_support;
+
+pub use value::{Value, BValue};
+pub use decoder::{read, read_strict, decode, decode_strict, decode_borrowed, Decoder, DecodeError};
+pub use decoder::{Reader, SliceReader, IoReader};
 pub use encoder::{write, encode};
+
+#[cfg(feature = "serde")]
+pub use serde_support::{to_bytes, from_bytes, SerdeError};
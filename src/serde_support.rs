@@ -0,0 +1,546 @@
+//! Optional [`serde`](https://serde.rs) support, enabled with the `serde`
+//! feature.
+//!
+//! `to_bytes` serializes any `Serialize` value into a bencode byte string by
+//! building a [`Value`](::Value) tree and handing it to [`encoder::write`]. `from_bytes`
+//! decodes a buffer with [`decoder::read`] and feeds the resulting `Value` to a
+//! `Deserialize` implementation. Byte strings map to `Vec<u8>` (or a caller's
+//! own `#[serde(with = "serde_bytes")]` wrapper, which rides on the same
+//! `serialize_bytes`/`deserialize_bytes` calls), and maps and structs map to
+//! bencode dictionaries (with the sorted-key ordering the encoder already
+//! guarantees).
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use serde::{ser, de};
+use serde::ser::SerializeSeq;
+
+use value::Value;
+use encoder;
+use decoder::{self, DecodeError};
+
+/// Error type shared by the serde serializer and deserializer. Wraps a
+/// [`DecodeError`] when decoding fails, and carries a free-form message for the
+/// errors serde itself raises.
+#[derive(Debug)]
+pub enum SerdeError {
+    Decode(DecodeError),
+    Message(String),
+}
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SerdeError::Decode(ref e) => write!(f, "bencode decode error: {:?}", e),
+            SerdeError::Message(ref m) => f.write_str(m),
+        }
+    }
+}
+
+impl error::Error for SerdeError {
+    fn description(&self) -> &str {
+        match *self {
+            SerdeError::Decode(_) => "bencode decode error",
+            SerdeError::Message(ref m) => m,
+        }
+    }
+}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SerdeError::Message(msg.to_string())
+    }
+}
+
+impl From<DecodeError> for SerdeError {
+    fn from(e: DecodeError) -> Self {
+        SerdeError::Decode(e)
+    }
+}
+
+/// Builds a `SerdeError` from a message. Used in place of
+/// `ser::Error::custom`/`de::Error::custom` at call sites: with both a
+/// reflexive `From<SerdeError>` and `From<DecodeError>` in scope, the bare
+/// `custom` calls leave the intermediate error type ambiguous.
+fn msg<T: fmt::Display>(m: T) -> SerdeError {
+    SerdeError::Message(m.to_string())
+}
+
+/// Serializes `value` into a bencode byte string.
+pub fn to_bytes<T: ser::Serialize>(value: &T) -> Result<Vec<u8>, SerdeError> {
+    let tree = try!(value.serialize(ValueSerializer));
+    Ok(encoder::encode(&tree))
+}
+
+/// Decodes a bencode byte string into a `T`.
+pub fn from_bytes<T>(buf: &[u8]) -> Result<T, SerdeError>
+    where T: de::DeserializeOwned
+{
+    let tree = try!(decoder::decode(buf));
+    T::deserialize(ValueDeserializer { value: tree })
+}
+
+/// A `serde::Serializer` that builds a `Value` tree.
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, SerdeError> {
+        Ok(Value::Integer(if v { 1 } else { 0 }))
+    }
+    fn serialize_i8(self, v: i8) -> Result<Value, SerdeError> { Ok(Value::Integer(v as i64)) }
+    fn serialize_i16(self, v: i16) -> Result<Value, SerdeError> { Ok(Value::Integer(v as i64)) }
+    fn serialize_i32(self, v: i32) -> Result<Value, SerdeError> { Ok(Value::Integer(v as i64)) }
+    fn serialize_i64(self, v: i64) -> Result<Value, SerdeError> { Ok(Value::Integer(v)) }
+    fn serialize_u8(self, v: u8) -> Result<Value, SerdeError> { Ok(Value::Integer(v as i64)) }
+    fn serialize_u16(self, v: u16) -> Result<Value, SerdeError> { Ok(Value::Integer(v as i64)) }
+    fn serialize_u32(self, v: u32) -> Result<Value, SerdeError> { Ok(Value::Integer(v as i64)) }
+    fn serialize_u64(self, v: u64) -> Result<Value, SerdeError> {
+        if v > i64::max_value() as u64 {
+            return Err(msg("u64 value does not fit in a bencode integer"));
+        }
+        Ok(Value::Integer(v as i64))
+    }
+    fn serialize_f32(self, _v: f32) -> Result<Value, SerdeError> {
+        Err(msg("bencode has no floating-point type"))
+    }
+    fn serialize_f64(self, _v: f64) -> Result<Value, SerdeError> {
+        Err(msg("bencode has no floating-point type"))
+    }
+    fn serialize_char(self, v: char) -> Result<Value, SerdeError> {
+        Ok(Value::String(v.to_string().into_bytes()))
+    }
+    fn serialize_str(self, v: &str) -> Result<Value, SerdeError> {
+        Ok(Value::String(v.as_bytes().to_vec()))
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SerdeError> {
+        Ok(Value::String(v.to_vec()))
+    }
+    fn serialize_none(self) -> Result<Value, SerdeError> {
+        // There is no null in bencode; an absent value is best modelled with an
+        // empty list so that it round-trips through `serialize_some`.
+        Ok(Value::List(Vec::new()))
+    }
+    fn serialize_some<T: ?Sized + ser::Serialize>(self, value: &T) -> Result<Value, SerdeError> {
+        Ok(Value::List(vec![try!(value.serialize(ValueSerializer))]))
+    }
+    fn serialize_unit(self) -> Result<Value, SerdeError> {
+        Ok(Value::List(Vec::new()))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SerdeError> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<Value, SerdeError> {
+        self.serialize_str(variant)
+    }
+    fn serialize_newtype_struct<T: ?Sized + ser::Serialize>(self, _name: &'static str, value: &T) -> Result<Value, SerdeError> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: ?Sized + ser::Serialize>(self, _name: &'static str, _index: u32, variant: &'static str, value: &T) -> Result<Value, SerdeError> {
+        let mut map = HashMap::new();
+        map.insert(variant.as_bytes().to_vec(), try!(value.serialize(ValueSerializer)));
+        Ok(Value::Dictionary(map))
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer { items: Vec::new(), variant: None })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer, SerdeError> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, variant: &'static str, _len: usize) -> Result<SeqSerializer, SerdeError> {
+        Ok(SeqSerializer { items: Vec::new(), variant: Some(variant) })
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer { map: HashMap::new(), next_key: None, variant: None })
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, SerdeError> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, variant: &'static str, _len: usize) -> Result<MapSerializer, SerdeError> {
+        Ok(MapSerializer { map: HashMap::new(), next_key: None, variant: Some(variant) })
+    }
+}
+
+/// Wraps `inner` in the single-entry dictionary `{variant: inner}` used to
+/// encode a non-unit enum variant, matching `serialize_newtype_variant` and the
+/// `EnumAccess` deserializer.
+fn wrap_variant(variant: &'static str, inner: Value) -> Value {
+    let mut map = HashMap::new();
+    map.insert(variant.as_bytes().to_vec(), inner);
+    Value::Dictionary(map)
+}
+
+struct SeqSerializer {
+    items: Vec<Value>,
+    variant: Option<&'static str>,
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.items.push(try!(value.serialize(ValueSerializer)));
+        Ok(())
+    }
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::List(self.items))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    fn serialize_element<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, SerdeError> { SerializeSeq::end(self) }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, SerdeError> { SerializeSeq::end(self) }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+    fn end(self) -> Result<Value, SerdeError> {
+        let variant = try!(self.variant.ok_or_else(|| msg("tuple variant serializer is missing its variant name")));
+        Ok(wrap_variant(variant, Value::List(self.items)))
+    }
+}
+
+struct MapSerializer {
+    map: HashMap<Vec<u8>, Value>,
+    next_key: Option<Vec<u8>>,
+    variant: Option<&'static str>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    fn serialize_key<T: ?Sized + ser::Serialize>(&mut self, key: &T) -> Result<(), SerdeError> {
+        match try!(key.serialize(ValueSerializer)) {
+            Value::String(s) => { self.next_key = Some(s); Ok(()) }
+            _ => Err(msg("bencode dictionary keys must be byte strings")),
+        }
+    }
+    fn serialize_value<T: ?Sized + ser::Serialize>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = try!(self.next_key.take().ok_or_else(|| msg("serialize_value called before serialize_key")));
+        self.map.insert(key, try!(value.serialize(ValueSerializer)));
+        Ok(())
+    }
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::Dictionary(self.map))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.map.insert(key.as_bytes().to_vec(), try!(value.serialize(ValueSerializer)));
+        Ok(())
+    }
+    fn end(self) -> Result<Value, SerdeError> {
+        Ok(Value::Dictionary(self.map))
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Value;
+    type Error = SerdeError;
+    fn serialize_field<T: ?Sized + ser::Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), SerdeError> {
+        self.map.insert(key.as_bytes().to_vec(), try!(value.serialize(ValueSerializer)));
+        Ok(())
+    }
+    fn end(self) -> Result<Value, SerdeError> {
+        let variant = try!(self.variant.ok_or_else(|| msg("struct variant serializer is missing its variant name")));
+        Ok(wrap_variant(variant, Value::Dictionary(self.map)))
+    }
+}
+
+/// A `serde::Deserializer` walking an already-decoded `Value` tree.
+struct ValueDeserializer {
+    value: Value,
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::String(s) => visitor.visit_byte_buf(s),
+            Value::List(l) => visitor.visit_seq(SeqAccess { iter: l.into_iter() }),
+            Value::Dictionary(d) => {
+                let mut items: Vec<(Vec<u8>, Value)> = d.into_iter().collect();
+                items.sort_by(|a, b| a.0.cmp(&b.0));
+                visitor.visit_map(MapAccess { iter: items.into_iter(), value: None })
+            }
+        }
+    }
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Integer(i) => visitor.visit_bool(i != 0),
+            _ => Err(msg("expected an integer for bool")),
+        }
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::String(s) => match String::from_utf8(s) {
+                Ok(string) => visitor.visit_string(string),
+                Err(e) => Err(msg(format!("string is not valid UTF-8: {}", e))),
+            },
+            _ => Err(msg("expected a byte string")),
+        }
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::String(s) => visitor.visit_byte_buf(s),
+            _ => Err(msg("expected a byte string")),
+        }
+    }
+
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            // An empty list is `None`; a one-element list is `Some`.
+            Value::List(ref l) if l.is_empty() => visitor.visit_none(),
+            Value::List(mut l) => visitor.visit_some(ValueDeserializer { value: l.remove(0) }),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: de::Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::List(l) => visitor.visit_seq(SeqAccess { iter: l.into_iter() }),
+            _ => Err(msg("expected a list")),
+        }
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.value {
+            Value::Dictionary(d) => {
+                let mut items: Vec<(Vec<u8>, Value)> = d.into_iter().collect();
+                items.sort_by(|a, b| a.0.cmp(&b.0));
+                visitor.visit_map(MapAccess { iter: items.into_iter(), value: None })
+            }
+            _ => Err(msg("expected a dictionary")),
+        }
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        visitor.visit_enum(EnumAccess { value: self.value })
+    }
+
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        self.deserialize_any(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char
+    }
+}
+
+struct SeqAccess {
+    iter: ::std::vec::IntoIter<Value>,
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = SerdeError;
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(ValueDeserializer { value }).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+struct MapAccess {
+    iter: ::std::vec::IntoIter<(Vec<u8>, Value)>,
+    value: Option<Value>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = SerdeError;
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(ValueDeserializer { value: Value::String(key) }).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, SerdeError> {
+        let value = try!(self.value.take().ok_or_else(|| msg("next_value called before next_key")));
+        seed.deserialize(ValueDeserializer { value })
+    }
+}
+
+struct EnumAccess {
+    value: Value,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = SerdeError;
+    type Variant = VariantAccess;
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, VariantAccess), SerdeError> {
+        match self.value {
+            // Unit variants are encoded as their name (a byte string).
+            Value::String(_) => {
+                let variant = try!(seed.deserialize(ValueDeserializer { value: self.value }));
+                Ok((variant, VariantAccess { value: None }))
+            }
+            // Other variants are a single-entry dictionary {name: payload}.
+            Value::Dictionary(d) => {
+                let mut items: Vec<(Vec<u8>, Value)> = d.into_iter().collect();
+                if items.len() != 1 {
+                    return Err(msg("expected a single-entry dictionary for an enum variant"));
+                }
+                let (key, value) = items.remove(0);
+                let variant = try!(seed.deserialize(ValueDeserializer { value: Value::String(key) }));
+                Ok((variant, VariantAccess { value: Some(value) }))
+            }
+            _ => Err(msg("expected a string or dictionary for an enum")),
+        }
+    }
+}
+
+struct VariantAccess {
+    value: Option<Value>,
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = SerdeError;
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, SerdeError> {
+        let value = try!(self.value.ok_or_else(|| msg("expected a newtype variant payload")));
+        seed.deserialize(ValueDeserializer { value })
+    }
+    fn tuple_variant<V: de::Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, SerdeError> {
+        let value = try!(self.value.ok_or_else(|| msg("expected a tuple variant payload")));
+        de::Deserializer::deserialize_seq(ValueDeserializer { value }, visitor)
+    }
+    fn struct_variant<V: de::Visitor<'de>>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> {
+        let value = try!(self.value.ok_or_else(|| msg("expected a struct variant payload")));
+        de::Deserializer::deserialize_map(ValueDeserializer { value }, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_bytes, from_bytes};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Message {
+        name: String,
+        count: i64,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn round_trip() {
+        let message = Message {
+            name: "announce".to_owned(),
+            count: 42,
+            tags: vec!["a".to_owned(), "b".to_owned()],
+        };
+        let bytes = to_bytes(&message).unwrap();
+        // Dictionary keys are emitted in sorted order.
+        assert_eq!(bytes, b"d5:counti42e4:name8:announce4:tagsl1:a1:bee".to_vec());
+        let decoded: Message = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    enum Shape {
+        Empty,
+        Radius(i64),
+        Pair(i64, i64),
+        Rect { w: i64, h: i64 },
+    }
+
+    fn assert_variant_round_trip(shape: Shape) {
+        let bytes = to_bytes(&shape).unwrap();
+        let decoded: Shape = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, shape);
+    }
+
+    #[test]
+    fn enum_variants() {
+        assert_variant_round_trip(Shape::Empty);
+        assert_variant_round_trip(Shape::Radius(5));
+        assert_variant_round_trip(Shape::Pair(1, 2));
+        assert_variant_round_trip(Shape::Rect { w: 3, h: 4 });
+    }
+}
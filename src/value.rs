@@ -8,3 +8,28 @@ pub enum Value {
     List(Vec<Value>),
     Dictionary(HashMap<Vec<u8>, Value>),
 }
+
+/// A borrowed counterpart of `Value` whose byte strings point directly into the
+/// buffer they were decoded from, avoiding a copy per string. Produced by
+/// `decode_borrowed`; call `to_owned` to turn it into an owning `Value`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BValue<'a> {
+    String(&'a [u8]),
+    Integer(i64),
+    List(Vec<BValue<'a>>),
+    Dictionary(HashMap<&'a [u8], BValue<'a>>),
+}
+
+impl<'a> BValue<'a> {
+    /// Copies the borrowed data out into an owning `Value`.
+    pub fn to_owned(&self) -> Value {
+        match *self {
+            BValue::String(s) => Value::String(s.to_vec()),
+            BValue::Integer(i) => Value::Integer(i),
+            BValue::List(ref l) => Value::List(l.iter().map(|v| v.to_owned()).collect()),
+            BValue::Dictionary(ref d) => Value::Dictionary(
+                d.iter().map(|(k, v)| (k.to_vec(), v.to_owned())).collect()
+            ),
+        }
+    }
+}